@@ -21,6 +21,45 @@ pub struct Cli {
     pub influx_token: String,
     #[clap(env)]
     pub token_cache_file: Option<String>,
+    /// Storage backend to write readings to.
+    #[arg(long, value_enum, default_value = "influx")]
+    pub sink: SinkKind,
+    /// Connection string for the `sql` sink (e.g. a Postgres or SQLite URL). Required when
+    /// `--sink sql` is set.
+    #[clap(env)]
+    pub sink_database_url: Option<String>,
+    /// Keep the process running and poll GlowMarkt on an interval instead of exiting after one run.
+    #[arg(long)]
+    pub daemon: bool,
+    /// Seconds between polls of a given resource when running in `--daemon` mode.
+    #[arg(long, default_value_t = 1800)]
+    pub poll_interval_secs: u64,
+    /// Seconds between refreshes of the entity/resource list when running in `--daemon` mode.
+    #[arg(long, default_value_t = 3600)]
+    pub entity_refresh_secs: u64,
+    /// Number of times to retry a GlowMarkt request that fails or returns a 429/5xx before
+    /// giving up on it.
+    #[arg(long, default_value_t = 5)]
+    pub retry_count: u32,
+    /// Base delay, in milliseconds, for the exponential backoff between retries.
+    #[arg(long, default_value_t = 500)]
+    pub retry_base_delay_ms: u64,
+    /// Maximum delay, in milliseconds, the exponential backoff between retries can reach before
+    /// random jitter of up to a quarter of that delay is added.
+    #[arg(long, default_value_t = 30_000)]
+    pub retry_max_delay_ms: u64,
+    /// Minimum delay, in milliseconds, to wait between successive resource requests.
+    #[arg(long, default_value_t = 250)]
+    pub request_delay_ms: u64,
+    /// Also fetch tariff data and emit a derived `cost` series alongside consumption readings.
+    #[arg(long)]
+    pub with_cost: bool,
+}
+
+#[derive(clap::ValueEnum, Clone, Debug)]
+pub enum SinkKind {
+    Influx,
+    Sql,
 }
 
 fn parse_dt(value: String) -> Result<chrono::DateTime<Local>, chrono::ParseError> {
@@ -1,24 +1,31 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
 use std::io::Write;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use chrono::{DateTime, Local, TimeZone};
 use clap::Parser;
-use influxdb::InfluxDbWriteable;
 use log::{debug, error, info};
 use reqwest::header;
 
 mod cli;
 mod models;
+mod retry;
+mod sink;
 
-use crate::cli::Cli;
-use crate::models::{Entity, Reading, ResourceQuery};
+use crate::cli::{Cli, SinkKind};
+use crate::models::{Entity, Reading, ResourceId, ResourceQuery, Tariff};
+use crate::retry::{send_with_retry, RetryConfig};
+use crate::sink::{InfluxSink, Sink, SqlSink};
 
 const GLOWMARKT_AUTH_URI: &str = "https://api.glowmarkt.com/api/v0-1/auth";
 const GLOWMARKT_APP_ID: &str = "b0f1b774-a586-4f72-9edd-27ead8aa7a8d";
 const DEFAULT_PERIOD: &str = "PT30M";
 const DEFAULT_FUNCTION: &str = "sum";
+/// How soon to retry the entity/resource list after a refresh fails, instead of waiting out a
+/// full `entity_refresh_secs` (which could otherwise leave the daemon idle for up to an hour on a
+/// transient startup failure).
+const ENTITY_REFRESH_RETRY_DELAY: Duration = Duration::from_secs(60);
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -33,21 +40,200 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .default_headers(headers)
         .build()?;
 
+    let sink: Box<dyn Sink> = match cli.sink {
+        SinkKind::Influx => {
+            let influx_client =
+                influxdb::Client::new(cli.influx_uri.clone(), cli.influx_database.clone())
+                    .with_token(cli.influx_token.clone());
+            Box::new(InfluxSink::new(influx_client))
+        }
+        SinkKind::Sql => {
+            let database_url = cli
+                .sink_database_url
+                .as_deref()
+                .ok_or("sink-database-url is required when --sink sql is set")?;
+            Box::new(SqlSink::connect(database_url).await?)
+        }
+    };
+
+    let retry_config = RetryConfig::from(&cli);
+    let request_delay = Duration::from_millis(cli.request_delay_ms);
+
+    if cli.daemon {
+        return run_daemon(&client, sink.as_ref(), &retry_config, request_delay, &cli).await;
+    }
+
     let (start, end) = get_date_range(&cli)?;
+
+    let last_written = if cli.start_date.is_some() && cli.end_date.is_some() {
+        HashMap::new()
+    } else {
+        sink.last_written_times().await.unwrap_or_else(|e| {
+            error!(
+                "Failed to read last written timestamps from the sink, falling back to the full window: {:?}",
+                e
+            );
+            HashMap::new()
+        })
+    };
+
     info!("Requesting data from GlowMarkt for {:?} - {:?}", start, end);
 
-    let batches = create_date_batches(start, end);
-    let readings = process_entities(&client, get_entities(&client).await?, &batches).await?;
+    let readings = process_entities(
+        &client,
+        get_entities(&client, &retry_config).await?,
+        start,
+        end,
+        &last_written,
+        &retry_config,
+        request_delay,
+        cli.with_cost,
+    )
+    .await?;
 
     if !readings.is_empty() {
-        let influx_client =
-            influxdb::Client::new(cli.influx_uri, cli.influx_database).with_token(cli.influx_token);
-        influx_client.query(readings).await?;
+        sink.write(&readings).await?;
     }
 
     Ok(())
 }
 
+/// Runs a continuous ingestion loop instead of exiting after one batch.
+///
+/// Resources are scheduled in a time-ordered queue keyed by the instant they are next due. Each
+/// iteration pops the earliest-due bucket (or sleeps until it arrives), fetches and writes
+/// readings for every resource in it, and reinserts each resource `poll_interval_secs` later. The
+/// entity list is periodically re-fetched so newly added resources get picked up without a
+/// restart.
+async fn run_daemon(
+    client: &reqwest::Client,
+    sink: &dyn Sink,
+    retry_config: &RetryConfig,
+    request_delay: Duration,
+    cli: &Cli,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let poll_interval = Duration::from_secs(cli.poll_interval_secs);
+    let entity_refresh_interval = Duration::from_secs(cli.entity_refresh_secs);
+
+    let mut schedule: BTreeMap<Instant, HashSet<ResourceId>> = BTreeMap::new();
+    // Every resource id ever enqueued, so a refresh only schedules resources we haven't seen
+    // before instead of re-enqueueing ones already waiting in a future bucket.
+    let mut scheduled: HashSet<ResourceId> = HashSet::new();
+    let mut next_entity_refresh = Instant::now();
+    let mut tariffs: HashMap<ResourceId, Tariff> = HashMap::new();
+
+    loop {
+        if Instant::now() >= next_entity_refresh {
+            let now = Instant::now();
+            next_entity_refresh = match get_entities(client, retry_config).await {
+                Ok(entities) => {
+                    for entity in entities {
+                        for resource in entity.resources {
+                            if scheduled.insert(resource.resource_id.clone()) {
+                                schedule.entry(now).or_default().insert(resource.resource_id);
+                            }
+                        }
+                    }
+                    now + entity_refresh_interval
+                }
+                Err(e) => {
+                    error!("Failed to refresh entity list: {:?}", e);
+                    now + ENTITY_REFRESH_RETRY_DELAY.min(entity_refresh_interval)
+                }
+            };
+        }
+
+        let Some(&next_run) = schedule.keys().next() else {
+            tokio::time::sleep(poll_interval).await;
+            continue;
+        };
+
+        let now = Instant::now();
+        if next_run > now {
+            tokio::time::sleep(next_run - now).await;
+            continue;
+        }
+
+        let due = schedule.remove(&next_run).unwrap();
+        for resource_id in due {
+            poll_resource(
+                client,
+                sink,
+                &resource_id,
+                poll_interval,
+                retry_config,
+                &mut tariffs,
+                cli.with_cost,
+            )
+            .await;
+            schedule
+                .entry(Instant::now() + poll_interval)
+                .or_default()
+                .insert(resource_id);
+            tokio::time::sleep(request_delay).await;
+        }
+    }
+}
+
+/// Fetches and writes the latest readings for a single resource, logging (rather than aborting
+/// the daemon loop on) any failure. Mirrors `process_entities`' cost-series behavior: when
+/// `with_cost` is set and the reading is a consumption one, a derived `cost` reading is computed
+/// and written alongside it, fetching (and caching) the resource's tariff on first use.
+async fn poll_resource(
+    client: &reqwest::Client,
+    sink: &dyn Sink,
+    resource_id: &ResourceId,
+    lookback: Duration,
+    retry_config: &RetryConfig,
+    tariffs: &mut HashMap<ResourceId, Tariff>,
+    with_cost: bool,
+) {
+    let now = Local::now();
+    let query = ResourceQuery {
+        from: (now - chrono::Duration::from_std(lookback).unwrap_or_default())
+            .format("%Y-%m-%dT%H:%M:%S")
+            .to_string(),
+        to: now.format("%Y-%m-%dT%H:%M:%S").to_string(),
+        period: DEFAULT_PERIOD.to_string(),
+        function: DEFAULT_FUNCTION.to_string(),
+    };
+
+    let readings = match get_readings_for_resource(client, resource_id, query, retry_config).await
+    {
+        Ok(readings) => readings,
+        Err(e) => {
+            error!("Failed to fetch readings for resource {}: {:?}", resource_id, e);
+            return;
+        }
+    };
+
+    let mut to_write = Vec::with_capacity(2);
+
+    if with_cost && readings.classifier().contains("consumption") {
+        if !tariffs.contains_key(resource_id) {
+            match get_tariff_for_resource(client, resource_id, retry_config).await {
+                Ok(tariff) => {
+                    tariffs.insert(resource_id.clone(), tariff);
+                }
+                Err(e) => error!("Failed to fetch tariff for resource {}: {:?}", resource_id, e),
+            }
+        }
+
+        if let Some(cost_reading) = tariffs
+            .get(resource_id)
+            .and_then(|tariff| readings.cost_reading(tariff))
+        {
+            to_write.push(cost_reading);
+        }
+    }
+
+    to_write.push(readings);
+
+    if let Err(e) = sink.write(&to_write).await {
+        error!("Failed to write readings for resource {}: {:?}", resource_id, e);
+    }
+}
+
 fn get_date_range(
     cli: &Cli,
 ) -> Result<(DateTime<Local>, DateTime<Local>), Box<dyn std::error::Error>> {
@@ -198,9 +384,12 @@ async fn get_auth(
         .await?)
 }
 
-async fn get_entities(client: &reqwest::Client) -> Result<Vec<Entity>, reqwest::Error> {
+async fn get_entities(
+    client: &reqwest::Client,
+    retry_config: &RetryConfig,
+) -> Result<Vec<Entity>, reqwest::Error> {
     let url = "https://api.glowmarkt.com/api/v0-1/virtualentity";
-    let response = client.get(url).send().await?;
+    let response = send_with_retry(retry_config, || client.get(url)).await?;
     let entities = response.json::<Vec<Entity>>().await?;
     Ok(entities)
 }
@@ -208,14 +397,31 @@ async fn get_entities(client: &reqwest::Client) -> Result<Vec<Entity>, reqwest::
 async fn process_entities(
     client: &reqwest::Client,
     entities: Vec<Entity>,
-    date_batches: &[(DateTime<Local>, DateTime<Local>)],
-) -> Result<Vec<influxdb::WriteQuery>, Box<dyn std::error::Error>> {
-    let mut influx = Vec::new();
+    fallback_start: DateTime<Local>,
+    end: DateTime<Local>,
+    last_written: &HashMap<ResourceId, DateTime<chrono::Utc>>,
+    retry_config: &RetryConfig,
+    request_delay: Duration,
+    with_cost: bool,
+) -> Result<Vec<Reading>, Box<dyn std::error::Error>> {
+    let mut readings_out = Vec::new();
+    let mut tariffs: HashMap<ResourceId, Tariff> = HashMap::new();
 
     for entity in entities {
         debug!("Processing entity: {:?}", entity);
         for resource in entity.resources {
-            for (from, to) in date_batches {
+            let start = last_written
+                .get(&resource.resource_id)
+                .map(|t| t.with_timezone(&Local) + chrono::Duration::seconds(1))
+                .unwrap_or(fallback_start);
+
+            if start >= end {
+                debug!("Resource {} is already up to date", resource.resource_id);
+                continue;
+            }
+
+            let date_batches = create_date_batches(start, end);
+            for (from, to) in &date_batches {
                 debug!("{:?} - {:?}", from, to);
                 let query = ResourceQuery {
                     from: from.format("%Y-%m-%dT%H:%M:%S").to_string(),
@@ -224,11 +430,38 @@ async fn process_entities(
                     function: DEFAULT_FUNCTION.to_string(),
                 };
 
-                match get_readings_for_resource(client, &resource.resource_id, query).await {
+                match get_readings_for_resource(client, &resource.resource_id, query, retry_config)
+                    .await
+                {
                     Ok(readings) => {
-                        for m in readings.to_influx() {
-                            influx.push(m.into_query("glowmarkt"));
+                        if with_cost && readings.classifier().contains("consumption") {
+                            if !tariffs.contains_key(&resource.resource_id) {
+                                match get_tariff_for_resource(
+                                    client,
+                                    &resource.resource_id,
+                                    retry_config,
+                                )
+                                .await
+                                {
+                                    Ok(tariff) => {
+                                        tariffs.insert(resource.resource_id.clone(), tariff);
+                                    }
+                                    Err(e) => error!(
+                                        "Failed to fetch tariff for resource {}: {:?}",
+                                        resource.resource_id, e
+                                    ),
+                                }
+                            }
+
+                            if let Some(cost_reading) = tariffs
+                                .get(&resource.resource_id)
+                                .and_then(|tariff| readings.cost_reading(tariff))
+                            {
+                                readings_out.push(cost_reading);
+                            }
                         }
+
+                        readings_out.push(readings);
                     }
                     Err(e) => {
                         error!(
@@ -237,29 +470,31 @@ async fn process_entities(
                         );
                     }
                 }
+
+                tokio::time::sleep(request_delay).await;
             }
         }
     }
 
-    Ok(influx)
+    Ok(readings_out)
 }
 
 async fn get_readings_for_resource(
     client: &reqwest::Client,
     resource_id: &str,
     query: ResourceQuery,
+    retry_config: &RetryConfig,
 ) -> Result<Reading, Box<dyn std::error::Error>> {
     let url = format!("https://api.glowmarkt.com/api/v0-1/resource/{resource_id}/readings?");
-    let response = client
-        .get(&url)
-        .query(&[
+    let response = send_with_retry(retry_config, || {
+        client.get(&url).query(&[
             ("from", &query.from),
             ("to", &query.to),
             ("period", &query.period),
             ("function", &query.function),
         ])
-        .send()
-        .await?;
+    })
+    .await?;
 
     let response_text = response.text().await?;
     debug!(
@@ -269,3 +504,13 @@ async fn get_readings_for_resource(
 
     Ok(serde_json::from_str::<Reading>(&response_text)?)
 }
+
+async fn get_tariff_for_resource(
+    client: &reqwest::Client,
+    resource_id: &str,
+    retry_config: &RetryConfig,
+) -> Result<Tariff, Box<dyn std::error::Error>> {
+    let url = format!("https://api.glowmarkt.com/api/v0-1/resource/{resource_id}/tariff");
+    let response = send_with_retry(retry_config, || client.get(&url)).await?;
+    Ok(response.json::<Tariff>().await?)
+}
@@ -1,9 +1,16 @@
 use std::collections::HashMap;
 
-use chrono::TimeZone;
+use chrono::{DateTime, TimeZone, Utc};
 use influxdb::InfluxDbWriteable;
 use serde::Deserialize;
 
+/// The number of `PT30M` readings GlowMarkt reports per day, used to apportion a tariff's daily
+/// standing charge across each half-hourly reading.
+const READINGS_PER_DAY: f64 = 48.0;
+
+/// A GlowMarkt resource id, as used to key scheduling and query state.
+pub type ResourceId = String;
+
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 #[allow(dead_code)]
@@ -55,14 +62,52 @@ pub struct ResourceQuery {
     pub function: String,
 }
 
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct TariffPeriod {
+    pub from: DateTime<Utc>,
+    pub to: Option<DateTime<Utc>>,
+    pub rate: f64,
+    #[serde(default)]
+    pub standing_charge: f64,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Tariff {
+    pub resource_id: String,
+    pub data: Vec<TariffPeriod>,
+}
+
+impl Tariff {
+    /// Returns the tariff period whose `[from, to)` window contains `time`, picking the most
+    /// recently effective one if several overlap (e.g. across a tariff change).
+    ///
+    /// Assumes the GlowMarkt `/tariff` response is a flat list of periods with `from`/`to`/`rate`/
+    /// `standingCharge` fields, as sketched in the API docs available at implementation time; this
+    /// hasn't been verified against a live response, so a differently-shaped payload will fail to
+    /// deserialize, `get_tariff_for_resource` returns the error, and its callers log it and skip
+    /// that resource's cost series.
+    pub fn period_at(&self, time: DateTime<Utc>) -> Option<&TariffPeriod> {
+        self.data
+            .iter()
+            .filter(|period| period.from <= time && period.to.is_none_or(|to| time < to))
+            .max_by_key(|period| period.from)
+    }
+}
+
 #[derive(InfluxDbWriteable, Clone, Default)]
 pub struct InfluxValue {
-    time: chrono::DateTime<chrono::Utc>,
-    value: f64,
+    pub(crate) time: chrono::DateTime<chrono::Utc>,
+    pub(crate) value: f64,
     #[influxdb(tag)]
     classifier: String,
     #[influxdb(tag)]
-    measurement: String,
+    pub(crate) resource_id: String,
+    #[influxdb(tag)]
+    units: String,
+    #[influxdb(tag)]
+    name: String,
 }
 
 impl Reading {
@@ -73,10 +118,57 @@ impl Reading {
                 time: chrono::Utc.timestamp_opt(v[0] as i64, 0).unwrap(),
                 value: v[1],
                 classifier: self.classifier.clone(),
-                measurement: self.classifier.clone(),
+                resource_id: self.resource_id.clone(),
+                units: self.units.clone(),
+                name: self.name.clone(),
             })
             .collect::<Vec<InfluxValue>>()
     }
+
+    pub fn resource_id(&self) -> &str {
+        &self.resource_id
+    }
+
+    pub fn classifier(&self) -> &str {
+        &self.classifier
+    }
+
+    pub fn units(&self) -> &str {
+        &self.units
+    }
+
+    /// Derives a `cost` reading from this (consumption) reading and a tariff, multiplying each
+    /// half-hourly value by the rate in effect at its timestamp and apportioning the tariff's
+    /// daily standing charge across every reading. Readings that fall outside all of the
+    /// tariff's periods (e.g. a tariff with no data for that day) are dropped. Returns `None` if
+    /// none of the readings could be priced.
+    pub fn cost_reading(&self, tariff: &Tariff) -> Option<Reading> {
+        let data: Vec<Vec<f64>> = self
+            .data
+            .iter()
+            .filter_map(|v| {
+                let time = chrono::Utc.timestamp_opt(v[0] as i64, 0).unwrap();
+                let period = tariff.period_at(time)?;
+                let cost = v[1] * period.rate + period.standing_charge / READINGS_PER_DAY;
+                Some(vec![v[0], cost])
+            })
+            .collect();
+
+        if data.is_empty() {
+            return None;
+        }
+
+        Some(Reading {
+            status: self.status.clone(),
+            name: self.name.clone(),
+            resource_type_id: self.resource_type_id.clone(),
+            resource_id: self.resource_id.clone(),
+            query: self.query.clone(),
+            data,
+            units: "pence".to_string(),
+            classifier: "cost".to_string(),
+        })
+    }
 }
 
 use thiserror::Error;
@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+use log::warn;
+use rand::Rng;
+use reqwest::StatusCode;
+
+use crate::cli::Cli;
+
+/// Retry/backoff settings for requests against GlowMarkt's (rate-limited) API.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryConfig {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl From<&Cli> for RetryConfig {
+    fn from(cli: &Cli) -> Self {
+        Self {
+            max_retries: cli.retry_count,
+            base_delay: Duration::from_millis(cli.retry_base_delay_ms),
+            max_delay: Duration::from_millis(cli.retry_max_delay_ms),
+        }
+    }
+}
+
+/// Sends a request built fresh by `make_request` on every attempt, retrying on transport errors
+/// or a 429/5xx response with exponential backoff (honoring a `Retry-After` header given in
+/// seconds; an HTTP-date value is ignored) up to `config.max_retries` times.
+pub async fn send_with_retry<F>(
+    config: &RetryConfig,
+    mut make_request: F,
+) -> Result<reqwest::Response, reqwest::Error>
+where
+    F: FnMut() -> reqwest::RequestBuilder,
+{
+    let mut attempt = 0;
+
+    loop {
+        let result = make_request().send().await;
+
+        let retryable = match &result {
+            Ok(response) => is_retryable_status(response.status()),
+            Err(e) => !e.is_builder(),
+        };
+
+        if !retryable || attempt >= config.max_retries {
+            return match result {
+                Ok(response) => response.error_for_status(),
+                Err(e) => Err(e),
+            };
+        }
+
+        let delay = result
+            .as_ref()
+            .ok()
+            .and_then(retry_after_delay)
+            .unwrap_or_else(|| backoff_delay(config, attempt));
+
+        attempt += 1;
+        warn!(
+            "Request failed, retrying in {:?} (attempt {}/{})",
+            delay, attempt, config.max_retries
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn retry_after_delay(response: &reqwest::Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
+
+fn backoff_delay(config: &RetryConfig, attempt: u32) -> Duration {
+    let exponential = config.base_delay.saturating_mul(1u32 << attempt.min(16));
+    let capped = exponential.min(config.max_delay);
+    let jitter = Duration::from_millis(rand::thread_rng().gen_range(0..=capped.as_millis() as u64 / 4));
+    capped + jitter
+}
@@ -0,0 +1,194 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use influxdb::InfluxDbWriteable;
+use sqlx::Row;
+use thiserror::Error;
+
+use crate::models::{Reading, ResourceId};
+
+#[derive(Error, Debug)]
+pub enum SinkError {
+    #[error("InfluxDB write failed: {0}")]
+    Influx(#[from] influxdb::Error),
+    #[error("Database write failed: {0}")]
+    Sql(#[from] sqlx::Error),
+}
+
+/// A destination for ingested readings. Implemented once per supported storage backend so
+/// `process_entities` doesn't need to know which one is in use.
+#[async_trait]
+pub trait Sink {
+    async fn write(&self, readings: &[Reading]) -> Result<(), SinkError>;
+
+    /// Returns the most recent `time` written for each resource, so a run can resume just past
+    /// where the last one left off instead of re-fetching the whole configured window. Each
+    /// implementation resolves this against its own backend, since resume state written to one
+    /// sink isn't visible from another.
+    async fn last_written_times(&self) -> Result<HashMap<ResourceId, DateTime<Utc>>, SinkError>;
+}
+
+pub struct InfluxSink {
+    client: influxdb::Client,
+}
+
+impl InfluxSink {
+    pub fn new(client: influxdb::Client) -> Self {
+        Self { client }
+    }
+}
+
+#[async_trait]
+impl Sink for InfluxSink {
+    async fn write(&self, readings: &[Reading]) -> Result<(), SinkError> {
+        let queries: Vec<_> = readings
+            .iter()
+            .flat_map(Reading::to_influx)
+            .map(|v| v.into_query("glowmarkt"))
+            .collect();
+
+        if queries.is_empty() {
+            return Ok(());
+        }
+
+        self.client.query(queries).await?;
+        Ok(())
+    }
+
+    async fn last_written_times(&self) -> Result<HashMap<ResourceId, DateTime<Utc>>, SinkError> {
+        #[derive(serde::Deserialize)]
+        struct LastWrite {
+            time: DateTime<Utc>,
+        }
+
+        let read_query = influxdb::ReadQuery::new(
+            "SELECT last(value) FROM glowmarkt GROUP BY resource_id, classifier",
+        );
+        let mut result = self.client.json_query(read_query).await?;
+        let parsed = result.deserialize_next::<LastWrite>()?;
+
+        // Grouped by classifier as well as resource_id, since a resource's classifiers (e.g.
+        // consumption vs. a derived cost series) can fall behind each other. Keep the earliest of
+        // the two per resource so resuming never skips a classifier that's behind the others.
+        let mut last_written: HashMap<ResourceId, DateTime<Utc>> = HashMap::new();
+        for series in parsed.series {
+            let Some(resource_id) = series.tags.as_ref().and_then(|tags| tags.get("resource_id"))
+            else {
+                continue;
+            };
+            let Some(row) = series.values.first() else {
+                continue;
+            };
+            last_written
+                .entry(resource_id.clone())
+                .and_modify(|t| *t = (*t).min(row.time))
+                .or_insert(row.time);
+        }
+
+        Ok(last_written)
+    }
+}
+
+const CREATE_READINGS_TABLE: &str = "CREATE TABLE IF NOT EXISTS readings (
+    time TEXT NOT NULL,
+    resource_id TEXT NOT NULL,
+    classifier TEXT NOT NULL,
+    units TEXT NOT NULL,
+    value DOUBLE PRECISION NOT NULL
+)";
+
+/// Writes readings to a relational database via SQLx, for users who'd rather ingest smart-meter
+/// data into Postgres/SQLite than run a time-series database.
+///
+/// `sqlx::Any` isn't used here: it neither rewrites bind placeholders per-backend nor supports
+/// encoding `chrono` types, so each backend gets its own pool and query text. `time` is stored as
+/// an RFC 3339 string so both backends can bind/read it without a chrono-aware driver feature.
+pub enum SqlSink {
+    Postgres(sqlx::PgPool),
+    Sqlite(sqlx::SqlitePool),
+}
+
+impl SqlSink {
+    pub async fn connect(database_url: &str) -> Result<Self, SinkError> {
+        if database_url.starts_with("postgres://") || database_url.starts_with("postgresql://") {
+            let pool = sqlx::PgPool::connect(database_url).await?;
+            sqlx::query(CREATE_READINGS_TABLE).execute(&pool).await?;
+            Ok(Self::Postgres(pool))
+        } else {
+            let pool = sqlx::SqlitePool::connect(database_url).await?;
+            sqlx::query(CREATE_READINGS_TABLE).execute(&pool).await?;
+            Ok(Self::Sqlite(pool))
+        }
+    }
+}
+
+#[async_trait]
+impl Sink for SqlSink {
+    async fn write(&self, readings: &[Reading]) -> Result<(), SinkError> {
+        match self {
+            SqlSink::Postgres(pool) => {
+                const INSERT: &str = "INSERT INTO readings (time, resource_id, classifier, units, value) VALUES ($1, $2, $3, $4, $5)";
+                for reading in readings {
+                    for value in reading.to_influx() {
+                        sqlx::query(INSERT)
+                            .bind(value.time.to_rfc3339())
+                            .bind(value.resource_id)
+                            .bind(reading.classifier().to_string())
+                            .bind(reading.units().to_string())
+                            .bind(value.value)
+                            .execute(pool)
+                            .await?;
+                    }
+                }
+            }
+            SqlSink::Sqlite(pool) => {
+                const INSERT: &str = "INSERT INTO readings (time, resource_id, classifier, units, value) VALUES (?, ?, ?, ?, ?)";
+                for reading in readings {
+                    for value in reading.to_influx() {
+                        sqlx::query(INSERT)
+                            .bind(value.time.to_rfc3339())
+                            .bind(value.resource_id)
+                            .bind(reading.classifier().to_string())
+                            .bind(reading.units().to_string())
+                            .bind(value.value)
+                            .execute(pool)
+                            .await?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn last_written_times(&self) -> Result<HashMap<ResourceId, DateTime<Utc>>, SinkError> {
+        const QUERY: &str =
+            "SELECT resource_id, MAX(time) AS time FROM readings GROUP BY resource_id";
+
+        let mut last_written = HashMap::new();
+
+        match self {
+            SqlSink::Postgres(pool) => {
+                for row in sqlx::query(QUERY).fetch_all(pool).await? {
+                    let resource_id: String = row.try_get("resource_id")?;
+                    let time: String = row.try_get("time")?;
+                    if let Ok(time) = DateTime::parse_from_rfc3339(&time) {
+                        last_written.insert(resource_id, time.with_timezone(&Utc));
+                    }
+                }
+            }
+            SqlSink::Sqlite(pool) => {
+                for row in sqlx::query(QUERY).fetch_all(pool).await? {
+                    let resource_id: String = row.try_get("resource_id")?;
+                    let time: String = row.try_get("time")?;
+                    if let Ok(time) = DateTime::parse_from_rfc3339(&time) {
+                        last_written.insert(resource_id, time.with_timezone(&Utc));
+                    }
+                }
+            }
+        }
+
+        Ok(last_written)
+    }
+}